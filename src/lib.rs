@@ -74,12 +74,319 @@
 #![deny(missing_docs, missing_debug_implementations)]
 
 use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub use arangors;
 pub use bb8;
 
 use arangors::{uclient, ClientError, Database, GenericConnection};
 use async_trait::async_trait;
+use base64::Engine;
+
+/// Default leeway applied before a JWT's reported expiry is treated as
+/// having already lapsed, to account for clock drift and in-flight requests.
+const DEFAULT_JWT_SKEW: Duration = Duration::from_secs(30);
+
+/// Shared liveness bookkeeping for [`Connection`] and [`ServerConnection`]:
+/// the JWT expiry computed at `connect` time, and a flag kept up to date by
+/// `is_valid` so the synchronous `has_broken` can read it back without
+/// making a network call of its own.
+#[derive(Debug)]
+struct Liveness {
+    expires_at: Option<Instant>,
+    broken: bool,
+}
+
+impl Liveness {
+    fn new(expires_at: Option<Instant>) -> Self {
+        Self {
+            expires_at,
+            broken: false,
+        }
+    }
+
+    /// Records the outcome of the most recent `is_valid` probe, so
+    /// `has_broken` can read it back synchronously.
+    fn mark_broken(&mut self, broken: bool) {
+        self.broken = broken;
+    }
+
+    /// Returns the liveness state last recorded by [`Liveness::mark_broken`].
+    fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Returns `true` once the JWT this connection was established with is
+    /// within `skew` of expiring. Always `false` for non-JWT connections.
+    fn is_token_expired(&self, skew: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expiry| Instant::now() + skew >= expiry)
+    }
+}
+
+/// Establishes a [`GenericConnection`] for `method`, returning the JWT
+/// expiry alongside it when the connection was authenticated with a token
+/// that carries (or `jwt_ttl` fills in) one. Shared by
+/// [`ArangoConnectionManager`], which binds the resulting connection to its
+/// database, and [`ArangoServerConnectionManager`], which hands it out as is.
+async fn establish<C: uclient::ClientExt + Send + 'static>(
+    url: &str,
+    method: &AuthenticationMethod,
+    jwt_ttl: Option<Duration>,
+) -> Result<(GenericConnection<C>, Option<Instant>), ClientError> {
+    match method {
+        AuthenticationMethod::BasicAuth(username, password) => Ok((
+            GenericConnection::establish_basic_auth(url, username, password).await?,
+            None,
+        )),
+        AuthenticationMethod::JWTAuth(username, password) => {
+            let conn = GenericConnection::establish_jwt(url, username, password).await?;
+            let expires_at = conn
+                .jwt()
+                .and_then(jwt_expiry)
+                .or_else(|| jwt_ttl.map(|ttl| Instant::now() + ttl));
+            Ok((conn, expires_at))
+        }
+        AuthenticationMethod::NoAuth => {
+            Ok((GenericConnection::establish_without_auth(url).await?, None))
+        }
+    }
+}
+
+/// A pooled ArangoDB connection.
+///
+/// Wraps the authenticated, business-facing [`Database`] handle (tagged per
+/// the manager's [`AsyncKind`], if any) together with the plain
+/// [`GenericConnection`] it was established from. When the business handle
+/// is tagged, a second, never-tagged `Database` handle on the same database
+/// is kept alongside it purely for health checks; when it isn't (the
+/// default [`AsyncKind::Blocking`]), the business handle is already plain
+/// and is reused, so `connect` only binds the database once.
+/// `is_valid` probes exclusively through the plain connection, so an
+/// `x-arango-async` tag never turns a health check's response into an HTTP
+/// 202 with no body. A liveness flag is kept up to date by [`is_valid`] so
+/// that the synchronous [`has_broken`] can report it without itself making
+/// a network call; since the flag is only ever written while this
+/// connection is checked out, eviction of a connection broken while sitting
+/// idle in the pool still requires `test_on_check_out` (or another explicit
+/// `is_valid` call) on the next checkout. For JWT-authenticated connections
+/// this also tracks when the token is due to expire, so the connection is
+/// recycled before ArangoDB starts rejecting it.
+///
+/// [`has_broken`]: bb8::ManageConnection::has_broken
+/// [`is_valid`]: bb8::ManageConnection::is_valid
+#[derive(Debug)]
+pub struct Connection<C: uclient::ClientExt> {
+    db: Database<C>,
+    health_db: Option<Database<C>>,
+    conn: GenericConnection<C>,
+    async_kind: AsyncKind,
+    liveness: Liveness,
+}
+
+impl<C: uclient::ClientExt> Connection<C> {
+    fn new(
+        conn: GenericConnection<C>,
+        db: Database<C>,
+        health_db: Option<Database<C>>,
+        expires_at: Option<Instant>,
+        async_kind: AsyncKind,
+    ) -> Self {
+        Self {
+            db,
+            health_db,
+            conn,
+            async_kind,
+            liveness: Liveness::new(expires_at),
+        }
+    }
+
+    /// Returns the [`AsyncKind`] the manager configured this connection's
+    /// HTTP session with, so callers issuing `store`-mode requests know how
+    /// to retrieve their results later.
+    pub fn async_kind(&self) -> AsyncKind {
+        self.async_kind
+    }
+
+    /// Returns the untagged `Database` handle used for health checks: the
+    /// dedicated one when the business handle is tagged, or the business
+    /// handle itself when it already is plain.
+    fn health_db(&self) -> &Database<C> {
+        self.health_db.as_ref().unwrap_or(&self.db)
+    }
+
+    /// Records the outcome of the most recent [`is_valid`] probe, so
+    /// [`has_broken`] can read it back synchronously.
+    ///
+    /// [`has_broken`]: bb8::ManageConnection::has_broken
+    /// [`is_valid`]: bb8::ManageConnection::is_valid
+    fn mark_broken(&mut self, broken: bool) {
+        self.liveness.mark_broken(broken);
+    }
+
+    /// Returns the liveness state last recorded by [`mark_broken`].
+    fn is_broken(&self) -> bool {
+        self.liveness.is_broken()
+    }
+
+    /// Returns `true` once the JWT backing this connection is within `skew`
+    /// of expiring. Always `false` for non-JWT connections.
+    fn is_token_expired(&self, skew: Duration) -> bool {
+        self.liveness.is_token_expired(skew)
+    }
+}
+
+/// Decodes the `exp` claim (seconds since the Unix epoch) out of a JWT,
+/// without verifying its signature; we only need to know when our own
+/// freshly-issued token runs out.
+fn jwt_expiry(token: &str) -> Option<Instant> {
+    let payload = token.split('.').nth(1)?;
+    let decoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&decoded).ok()?;
+    let exp = claims.get("exp")?.as_u64()?;
+
+    let expiry = UNIX_EPOCH + Duration::from_secs(exp);
+    let remaining = expiry.duration_since(SystemTime::now()).unwrap_or_default();
+    Some(Instant::now() + remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_jwt(claims: &serde_json::Value) -> String {
+        let payload =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(claims.to_string());
+        format!("header.{payload}.signature")
+    }
+
+    fn unix_now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn jwt_expiry_decodes_future_exp_claim() {
+        let token = fake_jwt(&serde_json::json!({ "exp": unix_now() + 3600 }));
+
+        let expiry = jwt_expiry(&token).expect("exp claim should be decoded");
+        assert!(expiry > Instant::now());
+    }
+
+    #[test]
+    fn jwt_expiry_returns_none_without_exp_claim() {
+        let token = fake_jwt(&serde_json::json!({ "sub": "root" }));
+
+        assert!(jwt_expiry(&token).is_none());
+    }
+
+    #[test]
+    fn jwt_expiry_handles_already_expired_claim() {
+        let token = fake_jwt(&serde_json::json!({ "exp": unix_now() - 3600 }));
+
+        let expiry = jwt_expiry(&token).expect("exp claim should be decoded");
+        assert!(expiry <= Instant::now());
+    }
+
+    #[test]
+    fn jwt_expiry_returns_none_for_malformed_base64() {
+        assert!(jwt_expiry("header.not-valid-base64!!!.signature").is_none());
+    }
+
+    #[test]
+    fn header_value_is_none_for_blocking() {
+        assert_eq!(AsyncKind::Blocking.header_value(), None);
+    }
+
+    #[test]
+    fn header_value_is_true_for_fire_and_forget() {
+        assert_eq!(AsyncKind::FireAndForget.header_value(), Some("true"));
+    }
+
+    #[test]
+    fn header_value_is_store_for_store() {
+        assert_eq!(AsyncKind::Store.header_value(), Some("store"));
+    }
+
+    #[test]
+    fn health_check_defaults_to_accessible_collections() {
+        assert!(matches!(
+            HealthCheck::default(),
+            HealthCheck::AccessibleCollections
+        ));
+    }
+}
+
+impl<C: uclient::ClientExt> std::ops::Deref for Connection<C> {
+    type Target = Database<C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+impl<C: uclient::ClientExt> std::ops::DerefMut for Connection<C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.db
+    }
+}
+
+/// Probe used by [`ManageConnection::is_valid`] to check out a pooled
+/// connection.
+///
+/// [`ManageConnection::is_valid`]: bb8::ManageConnection::is_valid
+#[derive(Debug, Clone)]
+pub enum HealthCheck {
+    /// Issue a lightweight `validate_server` ping against the ArangoDB
+    /// server, without touching the bound database.
+    Ping,
+    /// List the collections accessible to the authenticated user. The
+    /// default; relatively heavy, but exercises both authentication and the
+    /// bound database.
+    AccessibleCollections,
+    /// Run a caller-supplied AQL statement (e.g. `RETURN 1`) against the
+    /// bound database.
+    Aql(String),
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        HealthCheck::AccessibleCollections
+    }
+}
+
+/// Controls how ArangoDB executes requests issued over a pooled connection,
+/// via the `x-arango-async` header.
+///
+/// See the [async results management] documentation for the semantics of
+/// each mode.
+///
+/// [async results management]: https://www.arangodb.com/docs/stable/http/async-results-management.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncKind {
+    /// Execute requests synchronously and wait for the result. The default.
+    Blocking,
+    /// Queue the request and return immediately (HTTP 202) without keeping
+    /// the result.
+    FireAndForget,
+    /// Queue the request and keep the result available for later retrieval
+    /// by job id.
+    Store,
+}
+
+impl AsyncKind {
+    fn header_value(self) -> Option<&'static str> {
+        match self {
+            AsyncKind::Blocking => None,
+            AsyncKind::FireAndForget => Some("true"),
+            AsyncKind::Store => Some("store"),
+        }
+    }
+}
 
 /// Kind of the authentication method to use when establishing a connection.
 #[derive(Debug)]
@@ -101,6 +408,14 @@ pub struct ArangoConnectionManager<C: uclient::ClientExt> {
     method: AuthenticationMethod,
     phantom: PhantomData<C>,
     database: String,
+    /// Fallback token lifetime used when a JWT carries no `exp` claim.
+    jwt_ttl: Option<Duration>,
+    /// Leeway subtracted from a JWT's expiry before `has_broken` reports it.
+    jwt_skew: Duration,
+    /// Execution mode applied to the HTTP session's requests.
+    async_kind: AsyncKind,
+    /// Probe used by `is_valid` to check out a connection.
+    health_check: HealthCheck,
 }
 
 impl<C: uclient::ClientExt> ArangoConnectionManager<C> {
@@ -111,39 +426,266 @@ impl<C: uclient::ClientExt> ArangoConnectionManager<C> {
             method,
             phantom: PhantomData,
             database,
+            jwt_ttl: None,
+            jwt_skew: DEFAULT_JWT_SKEW,
+            async_kind: AsyncKind::Blocking,
+            health_check: HealthCheck::default(),
         }
     }
+
+    /// Sets the token lifetime assumed for `JWTAuth` connections whose JWT
+    /// does not carry an `exp` claim. Has no effect on other authentication
+    /// methods.
+    pub fn with_jwt_ttl(mut self, jwt_ttl: Duration) -> Self {
+        self.jwt_ttl = Some(jwt_ttl);
+        self
+    }
+
+    /// Sets the leeway applied before a JWT's expiry is treated as having
+    /// already lapsed. Defaults to 30 seconds.
+    pub fn with_jwt_skew(mut self, jwt_skew: Duration) -> Self {
+        self.jwt_skew = jwt_skew;
+        self
+    }
+
+    /// Sets the [`AsyncKind`] applied to requests issued over connections
+    /// from this manager. Defaults to [`AsyncKind::Blocking`].
+    pub fn with_async_kind(mut self, async_kind: AsyncKind) -> Self {
+        self.async_kind = async_kind;
+        self
+    }
+
+    /// Sets the probe `is_valid` uses to check out a connection. Defaults
+    /// to [`HealthCheck::AccessibleCollections`].
+    pub fn with_health_check(mut self, health_check: HealthCheck) -> Self {
+        self.health_check = health_check;
+        self
+    }
+
+    /// Establishes a connection for `self.method`, using the default HTTP
+    /// client. Used both for the plain connection backing health checks and,
+    /// when [`AsyncKind::Blocking`] is configured, for the business one too.
+    async fn establish(&self) -> Result<(GenericConnection<C>, Option<Instant>), ClientError> {
+        establish(&self.url, &self.method, self.jwt_ttl).await
+    }
+
+    /// Establishes a second connection for `self.method` over a caller-built
+    /// `client`, for the business-facing [`AsyncKind`]-tagged session. Only
+    /// used when `async_kind` is not [`AsyncKind::Blocking`]. The tagged
+    /// client backs only this second connection; the plain connection's
+    /// login handshake and every health-check probe use the default client.
+    async fn establish_with_client(&self, client: C) -> Result<GenericConnection<C>, ClientError> {
+        match &self.method {
+            AuthenticationMethod::BasicAuth(username, password) => {
+                GenericConnection::establish_basic_auth_with_client(
+                    &self.url, client, username, password,
+                )
+                .await
+            }
+            AuthenticationMethod::JWTAuth(username, password) => {
+                GenericConnection::establish_jwt_with_client(&self.url, client, username, password)
+                    .await
+            }
+            AuthenticationMethod::NoAuth => {
+                GenericConnection::establish_without_auth_with_client(&self.url, client).await
+            }
+        }
+    }
+
+    /// Builds an HTTP client tagged with the `x-arango-async` header for
+    /// `header_value`. Never used for the plain connection: baking the
+    /// header into default headers would also tag the login handshake and
+    /// every `is_valid` probe issued over the same client.
+    fn build_async_client(&self, header_value: &'static str) -> Result<C, ClientError> {
+        let mut headers = uclient::header::HeaderMap::new();
+        headers.insert(
+            uclient::header::HeaderName::from_static("x-arango-async"),
+            uclient::header::HeaderValue::from_static(header_value),
+        );
+        C::new(Some(headers))
+    }
 }
 
 #[async_trait]
 impl<C: uclient::ClientExt + Send + 'static> bb8::ManageConnection for ArangoConnectionManager<C> {
-    type Connection = Database<C>;
+    type Connection = Connection<C>;
     type Error = ClientError;
 
     async fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        let conn = match &self.method {
-            AuthenticationMethod::BasicAuth(username, password) => {
-                GenericConnection::establish_basic_auth(&self.url, username, password).await
+        let (conn, expires_at) = self.establish().await?;
+
+        let (db, health_db) = match self.async_kind.header_value() {
+            Some(header_value) => {
+                let client = self.build_async_client(header_value)?;
+                let async_conn = self.establish_with_client(client).await?;
+                let db = async_conn.db(&self.database).await?;
+                let health_db = conn.db(&self.database).await?;
+                (db, Some(health_db))
             }
-            AuthenticationMethod::JWTAuth(username, password) => {
-                GenericConnection::establish_jwt(&self.url, username, password).await
+            None => (conn.db(&self.database).await?, None),
+        };
+
+        Ok(Connection::new(
+            conn,
+            db,
+            health_db,
+            expires_at,
+            self.async_kind,
+        ))
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = match &self.health_check {
+            HealthCheck::Ping => {
+                let url = conn.conn.url().to_string();
+                let session = conn.conn.session();
+                GenericConnection::<C>::validate_server(&url, session).await
             }
-            AuthenticationMethod::NoAuth => {
-                GenericConnection::establish_without_auth(&self.url).await
+            HealthCheck::AccessibleCollections => {
+                conn.health_db().accessible_collections().await.map(|_| ())
             }
-        }?;
-        conn.db(&self.database).await
+            HealthCheck::Aql(statement) => conn
+                .health_db()
+                .aql_str::<serde_json::Value>(statement)
+                .await
+                .map(|_| ()),
+        };
+        conn.mark_broken(result.is_err());
+        result
     }
 
-    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
-        match conn.accessible_collections().await {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.is_token_expired(self.jwt_skew) || conn.is_broken()
+    }
+}
+
+/// A connection manager for ArangoDB that is not bound to any single
+/// database.
+///
+/// Unlike [`ArangoConnectionManager`], which hands out a [`Database`]
+/// handle for one fixed database, this manager checks out the authenticated
+/// [`GenericConnection`] itself. Callers that need to talk to several
+/// databases over the same pool can then call `conn.db("name").await` as
+/// needed, reusing a single authenticated HTTP session instead of running
+/// one pool per database.
+#[derive(Debug)]
+pub struct ArangoServerConnectionManager<C: uclient::ClientExt> {
+    url: String,
+    method: AuthenticationMethod,
+    phantom: PhantomData<C>,
+    /// Fallback token lifetime used when a JWT carries no `exp` claim.
+    jwt_ttl: Option<Duration>,
+    /// Leeway subtracted from a JWT's expiry before `has_broken` reports it.
+    jwt_skew: Duration,
+}
+
+impl<C: uclient::ClientExt> ArangoServerConnectionManager<C> {
+    /// Create a new ArangoServerConnectionManager.
+    pub fn new(url: String, method: AuthenticationMethod) -> Self {
+        Self {
+            url,
+            method,
+            phantom: PhantomData,
+            jwt_ttl: None,
+            jwt_skew: DEFAULT_JWT_SKEW,
+        }
+    }
+
+    /// Sets the token lifetime assumed for `JWTAuth` connections whose JWT
+    /// does not carry an `exp` claim. Has no effect on other authentication
+    /// methods.
+    pub fn with_jwt_ttl(mut self, jwt_ttl: Duration) -> Self {
+        self.jwt_ttl = Some(jwt_ttl);
+        self
+    }
+
+    /// Sets the leeway applied before a JWT's expiry is treated as having
+    /// already lapsed. Defaults to 30 seconds.
+    pub fn with_jwt_skew(mut self, jwt_skew: Duration) -> Self {
+        self.jwt_skew = jwt_skew;
+        self
+    }
+}
+
+/// A pooled, database-agnostic ArangoDB connection checked out from
+/// [`ArangoServerConnectionManager`].
+///
+/// Carries the same JWT-expiry and liveness tracking as [`Connection`], so
+/// JWT-authenticated server-level pools recycle connections before their
+/// token lapses instead of serving them indefinitely. As with [`Connection`],
+/// the liveness flag is only written while checked out, so evicting a
+/// connection broken while idle in the pool still requires
+/// `test_on_check_out` (or another explicit `is_valid` call) on the next
+/// checkout.
+#[derive(Debug)]
+pub struct ServerConnection<C: uclient::ClientExt> {
+    conn: GenericConnection<C>,
+    liveness: Liveness,
+}
+
+impl<C: uclient::ClientExt> ServerConnection<C> {
+    fn new(conn: GenericConnection<C>, expires_at: Option<Instant>) -> Self {
+        Self {
+            conn,
+            liveness: Liveness::new(expires_at),
         }
     }
 
-    fn has_broken(&self, _conn: &mut Self::Connection) -> bool {
-        false
+    /// Records the outcome of the most recent [`is_valid`] probe, so
+    /// [`has_broken`] can read it back synchronously.
+    ///
+    /// [`has_broken`]: bb8::ManageConnection::has_broken
+    /// [`is_valid`]: bb8::ManageConnection::is_valid
+    fn mark_broken(&mut self, broken: bool) {
+        self.liveness.mark_broken(broken);
+    }
+
+    /// Returns the liveness state last recorded by [`mark_broken`].
+    fn is_broken(&self) -> bool {
+        self.liveness.is_broken()
+    }
+
+    /// Returns `true` once the JWT backing this connection is within `skew`
+    /// of expiring. Always `false` for non-JWT connections.
+    fn is_token_expired(&self, skew: Duration) -> bool {
+        self.liveness.is_token_expired(skew)
+    }
+}
+
+impl<C: uclient::ClientExt> std::ops::Deref for ServerConnection<C> {
+    type Target = GenericConnection<C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conn
+    }
+}
+
+impl<C: uclient::ClientExt> std::ops::DerefMut for ServerConnection<C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.conn
+    }
+}
+
+#[async_trait]
+impl<C: uclient::ClientExt + Send + 'static> bb8::ManageConnection
+    for ArangoServerConnectionManager<C>
+{
+    type Connection = ServerConnection<C>;
+    type Error = ClientError;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let (conn, expires_at) = establish(&self.url, &self.method, self.jwt_ttl).await?;
+        Ok(ServerConnection::new(conn, expires_at))
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = conn.accessible_databases().await.map(|_| ());
+        conn.mark_broken(result.is_err());
+        result
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.is_token_expired(self.jwt_skew) || conn.is_broken()
     }
 }
 